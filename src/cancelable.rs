@@ -1,12 +1,12 @@
 use std::task::Context;
 use std::task::Poll;
+use std::task::Waker;
 use std::rc::Rc;
 use std::cell::Cell;
+use std::cell::RefCell;
 use std::future::Future;
 use std::pin::Pin;
 
-use futures::task::AtomicWaker;
-
 #[derive(Copy, Clone, Debug)]
 pub struct Cancelled;
 
@@ -17,7 +17,13 @@ pub struct CancelHandle {
 
 pub struct CancelInner {
 	cancelled: Cell<bool>,
-	waker: AtomicWaker,
+	/// Wakers of all tasks currently polling a [`Cancelable`] wrapping this handle.
+	///
+	/// A single handle can drive many `Cancelable` futures at once (one per spawned server task plus the
+	/// main application future), so we can't get away with a single `AtomicWaker` slot: registering a new
+	/// task would silently evict whichever task registered before it, and `cancel()` would only ever wake
+	/// the last one.
+	wakers: RefCell<Vec<Waker>>,
 }
 
 #[derive(Clone)]
@@ -27,18 +33,30 @@ pub struct Cancelable<Fut> {
 }
 
 impl CancelHandle {
-	fn new() -> Self {
+	pub fn new() -> Self {
 		Self {
 			inner: Rc::new(CancelInner {
 				cancelled: Cell::new(false),
-				waker: AtomicWaker::new(),
+				wakers: RefCell::new(Vec::new()),
 			}),
 		}
 	}
 
 	pub fn cancel(&self) {
 		self.inner.cancelled.set(true);
-		self.inner.waker.wake();
+		for waker in self.inner.wakers.borrow_mut().drain(..) {
+			waker.wake();
+		}
+	}
+
+	/// Wrap a future so that it resolves early with [`Cancelled`] once this handle is cancelled.
+	pub fn wrap<Fut: Future>(&self, future: Fut) -> Cancelable<Fut> {
+		Cancelable::new(future, self.clone())
+	}
+
+	/// Wait until this handle is cancelled.
+	pub async fn cancelled(&self) {
+		let _: Result<(), Cancelled> = self.wrap(std::future::pending()).await;
 	}
 }
 
@@ -75,7 +93,12 @@ impl<Fut: Future> Future for Cancelable<Fut> {
 			return Poll::Ready(Ok(x));
 		}
 
-		self.cancel.inner.waker.register(context.waker());
+		let mut wakers = self.cancel.inner.wakers.borrow_mut();
+		if !wakers.iter().any(|waker| waker.will_wake(context.waker())) {
+			wakers.push(context.waker().clone());
+		}
+		drop(wakers);
+
 		if self.cancel.inner.cancelled.get() {
 			Poll::Ready(Err(Cancelled))
 		} else {
@@ -86,6 +109,6 @@ impl<Fut: Future> Future for Cancelable<Fut> {
 
 pub fn cancelable<Fut: Future>(future: Fut) -> (Cancelable<Fut>, CancelHandle) {
 	let cancel = CancelHandle::new();
-	let cancelable = Cancelable::new(future, cancel.clone());
+	let cancelable = cancel.wrap(future);
 	(cancelable, cancel)
 }
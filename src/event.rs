@@ -1,6 +1,7 @@
 use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Event {
 	pub time: String,
 	pub model: String,
@@ -8,10 +9,18 @@ pub struct Event {
 	pub unit: u32,
 	pub id: u32,
 	pub channel: u32,
+	#[serde(serialize_with = "serialize_state")]
 	#[serde(deserialize_with = "deserialize_state")]
 	pub state: bool,
 }
 
+fn serialize_state<S>(state: &bool, ser: S) -> Result<S::Ok, S::Error>
+where
+	S: serde::Serializer
+{
+	ser.serialize_str(if *state { "ON" } else { "OFF" })
+}
+
 fn deserialize_state<'de, D>(de: D) -> Result<bool, D::Error>
 where
 	D: serde::Deserializer<'de>
@@ -1,19 +1,33 @@
 use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
 use std::process::ExitStatus;
 use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
 use structopt::StructOpt;
 use structopt::clap::AppSettings;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::net::UnixStream;
 use tokio::process::Child;
 use tokio::process::Command;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 
+mod cancelable;
+use cancelable::CancelHandle;
+
 mod event;
 use event::Event;
 
 pub mod server;
+use server::Listener;
+use server::Server;
 
 #[derive(StructOpt)]
 #[structopt(setting = AppSettings::ColoredHelp)]
@@ -73,6 +87,57 @@ struct Options {
 	#[structopt(long, short)]
 	#[structopt(value_name = "CHANNEL")]
 	channel: Option<u32>,
+
+	/// Also listen for subscribers on a TCP socket and broadcast decoded events to them.
+	#[structopt(long)]
+	#[structopt(value_name = "ADDRESS")]
+	listen_tcp: Option<SocketAddr>,
+
+	/// Also listen for subscribers on a Unix domain socket and broadcast decoded events to them.
+	#[structopt(long)]
+	#[structopt(value_name = "PATH")]
+	#[structopt(parse(from_os_str))]
+	listen_unix: Option<PathBuf>,
+
+	/// Suppress repeated events from the same sensor within this many milliseconds.
+	#[structopt(long)]
+	#[structopt(value_name = "MILLIS")]
+	debounce: Option<u64>,
+
+	/// When used with `--debounce`, an ON-to-OFF transition resets the debounce window immediately instead
+	/// of being suppressed, so a button release is never swallowed by the window.
+	#[structopt(long)]
+	debounce_trailing_edge: bool,
+
+	/// Automatically restart the rtl_433 process if it exits unexpectedly.
+	#[structopt(long)]
+	restart: bool,
+
+	/// Maximum delay in seconds between restart attempts.
+	///
+	/// The delay starts at one second and doubles after every failed attempt, up to this maximum.
+	#[structopt(long)]
+	#[structopt(default_value = "60")]
+	#[structopt(value_name = "SECONDS")]
+	restart_max_delay: u64,
+
+	/// Connect to a remote rtl433-doorbell server over TCP instead of launching rtl_433 locally.
+	///
+	/// The group/unit/id/channel filters and the action are applied the same way as in the normal mode,
+	/// letting several machines react to events decoded by a single machine with an SDR attached.
+	#[structopt(long)]
+	#[structopt(value_name = "ADDRESS")]
+	#[structopt(conflicts_with_all = &["rtl433-bin", "device", "restart", "connect-unix"])]
+	connect: Option<String>,
+
+	/// Connect to a remote rtl433-doorbell server over a Unix domain socket instead of launching rtl_433 locally.
+	///
+	/// See `--connect` for details.
+	#[structopt(long)]
+	#[structopt(value_name = "PATH")]
+	#[structopt(parse(from_os_str))]
+	#[structopt(conflicts_with_all = &["rtl433-bin", "device", "restart"])]
+	connect_unix: Option<PathBuf>,
 }
 
 fn main() {
@@ -80,16 +145,24 @@ fn main() {
 	let local = tokio::task::LocalSet::new();
 
 	let options = Options::from_args();
+	let cancel = CancelHandle::new();
 
 	let mut error = false;
 	let result = local.block_on(&rt, async {
-		let app = match Application::new(options) {
+		let app = match Application::new(options, cancel.clone()) {
 			Ok(x) => x,
 			Err(e) => {
 				eprintln!("{}", e);
 				std::process::exit(1);
 			},
 		};
+
+		tokio::task::spawn_local(wait_for_signal(cancel.clone()));
+
+		// `run` already observes `cancel` internally while it waits for events, for rtl_433 to exit and
+		// between restarts, and cleans up after itself, so it
+		// must be allowed to run to completion here rather than being raced against `cancel` again: a second
+		// `cancel.wrap` around it would pre-empt that cleanup the instant `cancel` fires.
 		app.run().await
 	});
 
@@ -98,24 +171,58 @@ fn main() {
 		error |= true;
 	}
 
-	// for action in &mut app.actions {
-	// 	let _ = action.kill();
-	// 	log_status_code("Action", action.await);
-	// }
-
 	if error {
 		std::process::exit(1);
 	}
 }
 
+/// Wait for SIGINT or SIGTERM and cancel `cancel` when one arrives.
+async fn wait_for_signal(cancel: CancelHandle) {
+	use tokio::signal::unix::SignalKind;
+
+	let mut sigint = match tokio::signal::unix::signal(SignalKind::interrupt()) {
+		Ok(x) => x,
+		Err(e) => return eprintln!("Failed to install SIGINT handler: {}", e),
+	};
+
+	let mut sigterm = match tokio::signal::unix::signal(SignalKind::terminate()) {
+		Ok(x) => x,
+		Err(e) => return eprintln!("Failed to install SIGTERM handler: {}", e),
+	};
+
+	tokio::select! {
+		_ = sigint.recv() => (),
+		_ = sigterm.recv() => (),
+	}
+
+	cancel.cancel();
+}
+
 struct Application {
 	options: Options,
-	child: Mutex<Child>,
+	/// The local `rtl_433` child process, or `None` in `--connect`/`--connect-unix` (client) mode.
+	child: Mutex<Option<Child>>,
 	actions: Mutex<BTreeMap<u32, JoinHandle<()>>>,
+	broadcaster: Mutex<Option<server::Broadcaster>>,
+	cancel: CancelHandle,
+	debounce: Mutex<BTreeMap<(u32, u32, u32, u32), DebounceEntry>>,
+}
+
+struct DebounceEntry {
+	last_fired: Instant,
+	last_state: bool,
+}
+
+/// Why reading from the `rtl_433` child's stdout stopped.
+enum ChildOutcome {
+	/// The child closed its stdout (it exited or crashed).
+	Exited,
+	/// We were cancelled before the child closed its stdout.
+	Cancelled,
 }
 
 impl Application {
-	fn new(options: Options) -> Result<Rc<Self>, String> {
+	fn spawn_rtl433(options: &Options) -> Result<Child, String> {
 		let mut command = Command::new(&options.rtl433_bin);
 		command.stdin(std::process::Stdio::null());
 		command.stdout(std::process::Stdio::piped());
@@ -130,39 +237,214 @@ impl Application {
 			command.args(&["-d", device]);
 		}
 
-		let child = command.spawn().map_err(|e| format!("Failed to run {:?}: {}", options.rtl433_bin, e))?;
+		command.spawn().map_err(|e| format!("Failed to run {:?}: {}", options.rtl433_bin, e))
+	}
+
+	fn new(options: Options, cancel: CancelHandle) -> Result<Rc<Self>, String> {
+		let child = match (&options.connect, &options.connect_unix) {
+			(None, None) => Some(Self::spawn_rtl433(&options)?),
+			_ => None,
+		};
 
 		Ok(Rc::new(Self {
 			options,
 			child: Mutex::new(child),
 			actions: Mutex::new(BTreeMap::new()),
+			broadcaster: Mutex::new(None),
+			cancel,
+			debounce: Mutex::new(BTreeMap::new()),
 		}))
 	}
 
+	/// Start the configured TCP and/or Unix domain socket servers, if any.
+	///
+	/// Both servers (if both are configured) share a single [`server::Broadcaster`] so that a `dingdong`
+	/// reaches subscribers regardless of which socket they connected to.
+	async fn start_servers(self: &Rc<Self>) -> Result<(), String> {
+		let (mut server, label) = match (&self.options.listen_tcp, &self.options.listen_unix) {
+			(None, None) => return Ok(()),
+			(Some(address), _) => (Server::bind(address).await
+				.map_err(|e| format!("Failed to listen on {}: {}", address, e))?, "TCP"),
+			(None, Some(path)) => (Server::new(Listener::bind_unix(path).await
+				.map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?), "Unix"),
+		};
+
+		*self.broadcaster.lock().await = Some(server.broadcaster());
+		let run = self.cancel.wrap(async move { server.run().await });
+		tokio::task::spawn_local(async move {
+			if let Ok(Err(e)) = run.await {
+				eprintln!("{} server stopped: {}", label, e);
+			}
+		});
+
+		if let (Some(_), Some(path)) = (&self.options.listen_tcp, &self.options.listen_unix) {
+			let listener = Listener::bind_unix(path).await
+				.map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+			let broadcaster = self.broadcaster.lock().await.clone().unwrap();
+			let mut server = Server::with_broadcaster(listener, broadcaster);
+			let run = self.cancel.wrap(async move { server.run().await });
+			tokio::task::spawn_local(async move {
+				if let Ok(Err(e)) = run.await {
+					eprintln!("Unix server stopped: {}", e);
+				}
+			});
+		}
+
+		Ok(())
+	}
+
 	async fn run(self: Rc<Self>) -> Result<(), String> {
+		self.start_servers().await?;
+
+		if let Some(address) = self.options.connect.clone() {
+			let stream = TcpStream::connect(&address).await
+				.map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+			let (read, write) = tokio::io::split(stream);
+			return self.run_client(&address, Box::new(read), Box::new(write)).await;
+		}
+
+		if let Some(path) = self.options.connect_unix.clone() {
+			let address = path.display().to_string();
+			let stream = UnixStream::connect(&path).await
+				.map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+			let (read, write) = tokio::io::split(stream);
+			return self.run_client(&address, Box::new(read), Box::new(write)).await;
+		}
+
+		let mut restart_delay = Duration::from_secs(1);
+		let mut started_at = Instant::now();
+
+		loop {
+			match self.read_rtl433_output().await? {
+				ChildOutcome::Cancelled => return Ok(()),
+				ChildOutcome::Exited => (),
+			}
+
+			let status = tokio::select! {
+				status = self.wait_for_rtl433_exit() => status,
+				_ = self.cancel.cancelled() => {
+					self.terminate_rtl433_and_actions().await;
+					return Ok(());
+				},
+			};
+			log_status_code(&self.options.rtl433_bin, status);
+
+			if !self.options.restart {
+				return Ok(());
+			}
+
+			// Only back off for rapid flapping: a child that stayed up longer than the current delay
+			// counts as a successful run, so the delay drops back to one second.
+			if started_at.elapsed() > restart_delay {
+				restart_delay = Duration::from_secs(1);
+			}
+
+			eprintln!("Restarting {} in {:?}.", self.options.rtl433_bin, restart_delay);
+			tokio::select! {
+				() = tokio::time::sleep(restart_delay) => (),
+				_ = self.cancel.cancelled() => {
+					self.terminate_rtl433_and_actions().await;
+					return Ok(());
+				},
+			}
+			restart_delay = (restart_delay * 2).min(Duration::from_secs(self.options.restart_max_delay));
+
+			*self.child.lock().await = Some(Self::spawn_rtl433(&self.options)?);
+			started_at = Instant::now();
+		}
+	}
+
+	async fn wait_for_rtl433_exit(&self) -> Result<ExitStatus, std::io::Error> {
+		self.child.lock().await.as_mut().expect("rtl_433 child missing").wait().await
+	}
+
+	/// SIGTERM the `rtl_433` child (if still alive) and all in-flight actions, then wait for the actions to exit.
+	async fn terminate_rtl433_and_actions(&self) {
+		if let Some(child) = self.child.lock().await.as_ref() {
+			if let Some(pid) = child.id() {
+				kill(pid, libc::SIGTERM);
+			}
+		}
+		self.terminate_actions().await;
+	}
+
+	/// Read and handle lines from the current `rtl_433` child until it closes its stdout or we're cancelled.
+	async fn read_rtl433_output(self: &Rc<Self>) -> Result<ChildOutcome, String> {
 		let mut child = self.child.lock().await;
+		let child = child.as_mut().expect("rtl_433 child missing");
 
 		let stream = child.stdout.as_mut().ok_or("No stdout available from child process.")?;
 		let stream = tokio::io::BufReader::new(stream);
 		let mut lines = stream.lines();
 
-		while let Some(message) = lines.next_line().await.map_err(|e| format!("Failed to read message from child: {}", e))? {
+		loop {
+			let message = tokio::select! {
+				line = lines.next_line() => line.map_err(|e| format!("Failed to read message from child: {}", e))?,
+				_ = self.cancel.cancelled() => {
+					if let Some(pid) = child.id() {
+						kill(pid, libc::SIGTERM);
+					}
+					self.terminate_actions().await;
+					return Ok(ChildOutcome::Cancelled);
+				},
+			};
+
+			let message = match message {
+				Some(message) => message,
+				None => return Ok(ChildOutcome::Exited),
+			};
+
 			let event = serde_json::from_str::<Event>(&message)
 				.map_err(|e| format!("Failed to parse message from child: {}", e))?;
 
-			if self.options.group.as_ref().map(|x| *x == event.group) == Some(false) {
+			if !self.event_passes_filters(&event) {
 				continue;
 			}
 
-			if self.options.unit.as_ref().map(|x| *x == event.unit) == Some(false) {
-				continue;
+			if let Some(broadcaster) = &*self.broadcaster.lock().await {
+				broadcaster.send_event(event.clone());
 			}
 
-			if self.options.id.as_ref().map(|x| *x == event.id) == Some(false) {
-				continue;
+			if let Err(e) = self.clone().run_action(&event).await {
+				eprintln!("{}", e);
 			}
+		}
+	}
 
-			if self.options.channel.as_ref().map(|x| *x == event.channel) == Some(false) {
+	/// Run actions for the events broadcast by a remote server, reached through an already-connected
+	/// TCP or Unix domain socket stream.
+	async fn run_client(
+		self: Rc<Self>,
+		address: &str,
+		read: Box<dyn AsyncRead + Unpin>,
+		mut write: Box<dyn AsyncWrite + Unpin>,
+	) -> Result<(), String> {
+		write.write_all(b"subscribe json\n").await
+			.map_err(|e| format!("Failed to send subscription to {}: {}", address, e))?;
+
+		let mut lines = tokio::io::BufReader::new(read).lines();
+
+		loop {
+			let line = tokio::select! {
+				line = lines.next_line() => line.map_err(|e| format!("Failed to read message from {}: {}", address, e))?,
+				_ = self.cancel.cancelled() => {
+					self.terminate_actions().await;
+					return Ok(());
+				},
+			};
+
+			let line = match line {
+				Some(line) => line,
+				None => return Err(format!("Connection to {} was closed.", address)),
+			};
+
+			// Ignore lines we don't understand, such as the legacy `dingdong` text framing.
+			let event = match serde_json::from_str::<Event>(&line) {
+				Ok(event) => event,
+				Err(_) => continue,
+			};
+
+			if !self.event_passes_filters(&event) {
 				continue;
 			}
 
@@ -170,11 +452,64 @@ impl Application {
 				eprintln!("{}", e);
 			}
 		}
+	}
 
-		Ok(())
+	fn event_passes_filters(&self, event: &Event) -> bool {
+		if self.options.group.as_ref().map(|x| *x == event.group) == Some(false) {
+			return false;
+		}
+
+		if self.options.unit.as_ref().map(|x| *x == event.unit) == Some(false) {
+			return false;
+		}
+
+		if self.options.id.as_ref().map(|x| *x == event.id) == Some(false) {
+			return false;
+		}
+
+		if self.options.channel.as_ref().map(|x| *x == event.channel) == Some(false) {
+			return false;
+		}
+
+		true
+	}
+
+	/// SIGTERM all in-flight actions and wait for them to exit.
+	async fn terminate_actions(&self) {
+		loop {
+			let (pid, join) = {
+				let mut actions = self.actions.lock().await;
+				let pid = match actions.iter().next() {
+					None => break,
+					Some((pid, _)) => *pid,
+				};
+				(pid, actions.remove(&pid).unwrap())
+			};
+			kill(pid, libc::SIGTERM);
+			let _ = join.await;
+		}
 	}
 
 	async fn run_action(self: Rc<Self>, event: &Event) -> Result<(), String> {
+		if let Some(debounce) = self.options.debounce {
+			let key = (event.group, event.unit, event.id, event.channel);
+			let mut debounced = self.debounce.lock().await;
+			match debounced.get_mut(&key) {
+				Some(entry) => {
+					let trailing_edge = self.options.debounce_trailing_edge && entry.last_state && !event.state;
+					if !trailing_edge && entry.last_fired.elapsed() < Duration::from_millis(debounce) {
+						entry.last_state = event.state;
+						return Ok(());
+					}
+					entry.last_fired = Instant::now();
+					entry.last_state = event.state;
+				},
+				None => {
+					debounced.insert(key, DebounceEntry { last_fired: Instant::now(), last_state: event.state });
+				},
+			}
+		}
+
 		if self.options.skip_busy {
 			let actions = self.actions.lock().await;
 			if !actions.is_empty() {
@@ -1,22 +1,37 @@
+use std::cell::Cell;
 use std::future::Future;
+use std::io;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
 use tokio::io::AsyncBufRead;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufReader;
 use tokio::io::BufWriter;
 use tokio::net::TcpListener;
 use tokio::net::ToSocketAddrs;
+use tokio::net::UnixListener;
 use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 
+use crate::event::Event;
+
 pub struct Server {
-	listener: TcpListener,
+	listener: Listener,
 	events: broadcast::Sender<Message>,
 	tasks: Vec<JoinHandle<std::io::Result<()>>>,
 }
 
+/// A listening socket, either a TCP socket or a Unix domain socket.
+pub enum Listener {
+	Tcp(TcpListener),
+	Unix(UnixListener),
+}
+
 #[derive(Clone, Debug)]
 pub struct Broadcaster {
 	sender: broadcast::Sender<Message>,
@@ -24,18 +39,60 @@ pub struct Broadcaster {
 
 #[derive(Clone, Debug)]
 enum Source {
-	Internal,
-	Socket(SocketAddr),
+	Tcp(SocketAddr),
+	Unix(Option<PathBuf>),
 }
 
 #[derive(Clone, Debug)]
 enum Message {
 	DingDong(Source),
+	Event(Event),
 }
 
+/// How a connected client wants events framed, chosen through a `subscribe` greeting line.
+#[derive(Copy, Clone, Debug)]
+enum Framing {
+	/// One literal `dingdong\n` line per event, for backward compatibility with old clients.
+	DingDong,
+	/// One JSON-encoded `Event` per line.
+	Json,
+}
+
+impl Listener {
+	pub async fn bind_tcp(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+		Ok(Self::Tcp(TcpListener::bind(address).await?))
+	}
+
+	/// Bind a Unix domain socket, removing a stale socket file left over from a previous run.
+	pub async fn bind_unix(path: impl AsRef<Path>) -> std::io::Result<Self> {
+		let path = path.as_ref();
+		match std::fs::remove_file(path) {
+			Ok(()) => (),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+			Err(e) => return Err(e),
+		}
+		Ok(Self::Unix(UnixListener::bind(path)?))
+	}
+
+	async fn accept(&self) -> std::io::Result<(Box<dyn AsyncRead + Unpin>, Box<dyn AsyncWrite + Unpin>, Source)> {
+		match self {
+			Self::Tcp(listener) => {
+				let (stream, address) = listener.accept().await?;
+				let (read, write) = tokio::io::split(stream);
+				Ok((Box::new(read), Box::new(write), Source::Tcp(address)))
+			},
+			Self::Unix(listener) => {
+				let (stream, address) = listener.accept().await?;
+				let path = address.as_pathname().map(PathBuf::from);
+				let (read, write) = tokio::io::split(stream);
+				Ok((Box::new(read), Box::new(write), Source::Unix(path)))
+			},
+		}
+	}
+}
 
 impl Server {
-	pub fn new(listener: TcpListener) -> Self {
+	pub fn new(listener: Listener) -> Self {
 		let (events, _) = broadcast::channel(10);
 		Self {
 			listener,
@@ -44,9 +101,19 @@ impl Server {
 		}
 	}
 
+	/// Create a server for `listener` that broadcasts on an existing [`Broadcaster`].
+	///
+	/// This allows multiple servers (for example one TCP and one Unix listener) to share a single stream of events.
+	pub fn with_broadcaster(listener: Listener, broadcaster: Broadcaster) -> Self {
+		Self {
+			listener,
+			events: broadcaster.sender,
+			tasks: Vec::new(),
+		}
+	}
+
 	pub async fn bind(address: impl ToSocketAddrs) -> std::io::Result<Self> {
-		let listener = TcpListener::bind(address).await?;
-		Ok(Self::new(listener))
+		Ok(Self::new(Listener::bind_tcp(address).await?))
 	}
 
 	pub async fn run(&mut self) -> std::io::Result<()> {
@@ -70,17 +137,18 @@ impl Server {
 	}
 
 	async fn accept_one(&mut self) -> std::io::Result<()> {
-		let (stream, address) = self.listener.accept().await?;
-		let (read, write) = tokio::io::split(stream);
+		let (read, write, source) = self.listener.accept().await?;
+		let framing = Rc::new(Cell::new(Framing::DingDong));
 
-		self.spawn(Self::run_read_loop(address, BufReader::new(read), self.events.clone()));
-		self.spawn(Self::run_write_loop(address, BufWriter::new(write), self.events.subscribe()));
+		self.spawn(Self::run_read_loop(source.clone(), framing.clone(), BufReader::new(read), self.events.clone()));
+		self.spawn(Self::run_write_loop(source, framing, BufWriter::new(write), self.events.subscribe()));
 
 		Ok(())
 	}
 
 	async fn run_read_loop(
-		address: SocketAddr,
+		source: Source,
+		framing: Rc<Cell<Framing>>,
 		read: impl AsyncBufRead + Unpin,
 		sender: broadcast::Sender<Message>
 	) -> std::io::Result<()> {
@@ -88,8 +156,10 @@ impl Server {
 		while let Some(line) = lines.next_line().await? {
 			match line.as_str() {
 				"dingdong" => {
-					let _ = sender.send(Message::DingDong(Source::Socket(address)));
+					let _ = sender.send(Message::DingDong(source.clone()));
 				},
+				"subscribe dingdong" => framing.set(Framing::DingDong),
+				"subscribe json" => framing.set(Framing::Json),
 				_ => (),
 			}
 		}
@@ -98,7 +168,8 @@ impl Server {
 	}
 
 	async fn run_write_loop(
-		_address: SocketAddr,
+		_source: Source,
+		framing: Rc<Cell<Framing>>,
 		mut write: impl AsyncWrite + Unpin,
 		mut receiver: broadcast::Receiver<Message>
 	) -> std::io::Result<()> {
@@ -109,19 +180,28 @@ impl Server {
 				Ok(address) => address,
 			};
 
-			match message {
-				Message::DingDong(_) => {
+			match (message, framing.get()) {
+				(Message::DingDong(_), Framing::DingDong) => {
 					write.write_all(b"dingdong\n").await?;
-					write.flush().await?;
+				},
+				(Message::DingDong(_), Framing::Json) => (),
+				(Message::Event(_), Framing::DingDong) => {
+					write.write_all(b"dingdong\n").await?;
+				},
+				(Message::Event(event), Framing::Json) => {
+					let line = serde_json::to_string(&event).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+					write.write_all(line.as_bytes()).await?;
+					write.write_all(b"\n").await?;
 				},
 			}
+			write.flush().await?;
 		}
 		Ok(())
 	}
 }
 
 impl Broadcaster {
-	pub fn send_ding_dong(&self) {
-		let _ = self.sender.send(Message::DingDong(Source::Internal));
+	pub fn send_event(&self, event: Event) {
+		let _ = self.sender.send(Message::Event(event));
 	}
 }